@@ -1,8 +1,50 @@
-use near_sdk::{env, near, AccountId};
-use near_sdk::store::{Vector, UnorderedMap};
+use std::collections::HashMap;
+
+use near_sdk::{env, near, AccountId, NearToken, PanicOnDefault, Promise};
+use near_sdk::store::{LookupMap, Vector, UnorderedMap};
+
+use pricing::Price;
+
+// NEW: Default for how long a buyer's deposit sits in escrow before they can
+// reclaim it via `refund` even without an explicit `revoke_buyer_access` from
+// the owner. Tunable per-deployment via `set_escrow_timeout`.
+const DEFAULT_ESCROW_TIMEOUT_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// NEW: Exact rational pricing so listings aren't limited to whole-NEAR,
+// integer-only amounts. Conversion to yoctoNEAR is overflow-checked and
+// rounds down when the fraction is not a whole number of yoctoNEAR.
+pub mod pricing {
+    use near_sdk::near;
+
+    const YOCTO_PER_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+    /// A price expressed as the exact fraction `numerator / denominator` of NEAR.
+    #[near(serializers = [json, borsh])]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Price {
+        pub numerator: u128,
+        pub denominator: u128,
+    }
+
+    impl Price {
+        pub fn new(numerator: u128, denominator: u128) -> Self {
+            assert!(denominator > 0, "Price denominator must be non-zero");
+            Self { numerator, denominator }
+        }
+
+        /// Checked conversion to the yoctoNEAR amount this price represents,
+        /// rounded down to a whole yoctoNEAR. Panics on overflow.
+        pub fn to_yocto(&self) -> u128 {
+            self.numerator
+                .checked_mul(YOCTO_PER_NEAR)
+                .and_then(|scaled| scaled.checked_div(self.denominator))
+                .expect("Price conversion to yoctoNEAR overflowed")
+        }
+    }
+}
 
 #[near(serializers = [json, borsh])]
-#[derive(Clone)] 
+#[derive(Clone)]
 pub enum ListingKind {
     Image,
     Dataset,
@@ -10,11 +52,32 @@ pub enum ListingKind {
     Other,
 }
 
+// NEW: Following the SNIP-721 access model, a grant can be permanent or lapse
+// on its own once a block height/timestamp is reached, so owners no longer
+// have to remember to call `revoke_buyer_access`.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Expiration {
+    Never,
+    AtHeight(u64),
+    AtTime(u64),
+}
+
+impl Expiration {
+    fn is_expired(&self) -> bool {
+        match self {
+            Expiration::Never => false,
+            Expiration::AtHeight(height) => env::block_height() >= *height,
+            Expiration::AtTime(time) => env::block_timestamp() >= *time,
+        }
+    }
+}
+
 #[near(serializers = [json, borsh])]
-#[derive(Clone)] 
+#[derive(Clone)]
 pub struct Listing {
     pub product_id: u64,
-    pub price: u32,
+    pub price: Price,
     pub nova_group_id: String,
     pub owner: AccountId,
     pub purchase_number: u32,
@@ -22,100 +85,541 @@ pub struct Listing {
     pub cid: String,
     pub is_active: bool,
     pub buyers: Vec<AccountId>,
-    pub buyers_with_access: Vec<AccountId>,
+    pub buyers_with_access: HashMap<AccountId, Expiration>,
     pub is_tee_verified: bool,
     pub tee_signature: Option<String>,
 }
 
+// NEW: A buyer's deposit held until the owner grants access, the owner
+// revokes before granting, or `escrow_timeout_ns` elapses.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct EscrowEntry {
+    pub amount: u128,
+    pub deposited_at: u64,
+    pub revoked: bool,
+}
+
+// NEW: A single listing's fields for `create_listings`, mirroring
+// `create_listing`'s parameters so batches and single creates stay in sync.
+#[near(serializers = [json])]
+pub struct ListingInput {
+    pub product_id: u64,
+    pub price: Price,
+    pub nova_group_id: String,
+    pub list_type: ListingKind,
+    pub cid: String,
+    pub gp_owner: AccountId,
+    pub tee_key_id: Option<String>,
+    pub tee_signature: Option<String>,
+}
+
+// NEW: Per-item outcome of a batch operation, so a partial batch reports
+// which entries were rejected instead of failing the whole transaction.
+#[near(serializers = [json])]
+pub struct BatchItemResult {
+    pub product_id: u64,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+// NEW: The kind of action an append-only `MarketEvent` records.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub enum EventKind {
+    Purchase,
+    GrantAccess,
+    RevokeAccess,
+}
+
+// NEW: An immutable audit record appended for every purchase and access
+// change, so disputes can be resolved from on-chain history.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct MarketEvent {
+    pub kind: EventKind,
+    pub product_id: u64,
+    pub actor: AccountId,
+    pub counterparty: Option<AccountId>,
+    pub timestamp: u64,
+}
+
 #[near(contract_state)]
+#[derive(PanicOnDefault)]
 pub struct Contract {
     listings: Vector<Listing>,
     // NEW: Map NEAR wallet → NOVA account ID
     // Key: NEAR wallet (e.g., "buyer.near")
     // Value: NOVA account (e.g., "buyer123.nova-sdk.near")
     nova_account_map: UnorderedMap<AccountId, String>,
+    // Secondary-index layer: avoids the O(n) scan over `listings` that every
+    // read/write method used to pay for. `listing_index` gives O(1) lookup of
+    // a listing's slot in the Vector; the by-owner/by-buyer maps resolve the
+    // same way for the `get_listings_by_owner`/`get_purchased_listings` views.
+    listing_index: LookupMap<u64, u32>,
+    listings_by_owner: UnorderedMap<AccountId, Vec<u64>>,
+    listings_by_buyer: UnorderedMap<AccountId, Vec<u64>>,
+    // NEW: Ed25519 public keys of enclaves trusted to attest listings, keyed
+    // by an operator-chosen key id. Populated by `register_trusted_enclave`.
+    trusted_enclaves: UnorderedMap<String, Vec<u8>>,
+    // NEW: Account allowed to manage `trusted_enclaves` and `escrow_timeout_ns`.
+    admin: AccountId,
+    // NEW: How long, in nanoseconds, a deposit sits in escrow before `refund`
+    // allows reclaiming it without an explicit `revoke_buyer_access`.
+    // Admin-configurable via `set_escrow_timeout`.
+    escrow_timeout_ns: u64,
+    // NEW: Deposits escrowed by `buy`, keyed by (product_id, buyer), released
+    // by `grant_buyer_access` or reclaimed by `refund`.
+    escrow: UnorderedMap<(u64, AccountId), EscrowEntry>,
+    // NEW: Ed25519 public keys owners register so `access_with_permit` can
+    // verify permits signed off-chain with their NEAR key.
+    owner_access_keys: UnorderedMap<AccountId, Vec<u8>>,
+    // NEW: Permit signatures an owner has invalidated, keyed by owner. Lets an
+    // owner kill one specific outstanding permit before it's ever redeemed.
+    revoked_permits: UnorderedMap<AccountId, Vec<String>>,
+    // NEW: A monotonically-increasing epoch per (product_id, buyer). A permit
+    // is only valid for the epoch it was signed under, so bumping this on
+    // `revoke_buyer_access` invalidates every outstanding permit for that
+    // pair at once — not just the one redemption the contract happened to see.
+    permit_epochs: UnorderedMap<(u64, AccountId), u64>,
+    // NEW: Append-only log of purchase/access events for auditing.
+    events: Vector<MarketEvent>,
+}
+
+// NEW: A buyer has access only while their grant is present and unexpired.
+fn has_valid_access(listing: &Listing, buyer: &AccountId) -> bool {
+    listing
+        .buyers_with_access
+        .get(buyer)
+        .map(|expiration| !expiration.is_expired())
+        .unwrap_or(false)
+}
+
+// NEW: Decode a lowercase/uppercase hex-encoded signature into raw bytes.
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// NEW: Verify a hex-encoded ed25519 `signature` over `message` against a raw
+// 32-byte public key. Shared by TEE attestation and permit verification.
+fn verify_ed25519(pubkey: &[u8], message: &[u8], signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    let (Ok(signature), Ok(pubkey)) = (
+        <[u8; 64]>::try_from(signature.as_slice()),
+        <[u8; 32]>::try_from(pubkey),
+    ) else {
+        return false;
+    };
+    env::ed25519_verify(&signature, message, &pubkey)
 }
 
-impl Default for Contract {
-    fn default() -> Self {
+// NEW: Canonical message an owner signs off-chain to grant `buyer` access to
+// `product_id` until `expiration`, redeemable via `access_with_permit`.
+fn permit_message(product_id: u64, buyer: &AccountId, expiration: Expiration, epoch: u64) -> Vec<u8> {
+    near_sdk::borsh::to_vec(&(product_id, buyer, expiration, epoch)).expect("Permit message serialization failed")
+}
+
+// NEW: Canonical message a TEE enclave signs to attest a listing. Borsh-encodes
+// the fields as a length-prefixed tuple instead of concatenating them, so
+// distinct (product_id, cid, nova_group_id) triples can never collide onto
+// the same signed bytes.
+fn tee_attestation_message(product_id: u64, cid: &str, nova_group_id: &str) -> Vec<u8> {
+    near_sdk::borsh::to_vec(&(product_id, cid, nova_group_id)).expect("TEE attestation message serialization failed")
+}
+
+#[near]
+impl Contract {
+    #[init]
+    pub fn new(admin: AccountId) -> Self {
         Self {
             listings: Vector::new(b"l"),
             nova_account_map: UnorderedMap::new(b"n"),
+            listing_index: LookupMap::new(b"i"),
+            listings_by_owner: UnorderedMap::new(b"o"),
+            listings_by_buyer: UnorderedMap::new(b"u"),
+            trusted_enclaves: UnorderedMap::new(b"e"),
+            admin,
+            escrow_timeout_ns: DEFAULT_ESCROW_TIMEOUT_NS,
+            escrow: UnorderedMap::new(b"s"),
+            owner_access_keys: UnorderedMap::new(b"k"),
+            revoked_permits: UnorderedMap::new(b"r"),
+            permit_epochs: UnorderedMap::new(b"p"),
+            events: Vector::new(b"v"),
         }
     }
-}
 
-#[near]
-impl Contract {
+    // NEW: Admin-gated override of how long deposits sit in escrow before
+    // `refund` allows reclaiming them without an explicit revoke.
+    pub fn set_escrow_timeout(&mut self, escrow_timeout_ns: u64) {
+        assert_eq!(
+            env::predecessor_account_id(), self.admin,
+            "Only the contract admin can set the escrow timeout"
+        );
+        self.escrow_timeout_ns = escrow_timeout_ns;
+    }
+
+    // NEW: Paginated event history, most recent entries appended last.
+    pub fn get_events(&self, from_index: u32, limit: u32) -> Vec<MarketEvent> {
+        let end = std::cmp::min(from_index.saturating_add(limit), self.events.len() as u32);
+        (from_index..end).filter_map(|i| self.events.get(i).cloned()).collect()
+    }
+
+    // NEW: Paginated event history scoped to a single product.
+    pub fn get_events_for_product(&self, p_id: u64, from_index: u32, limit: u32) -> Vec<MarketEvent> {
+        self.events
+            .iter()
+            .filter(|event| event.product_id == p_id)
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    // NEW: Owners self-register the ed25519 key they'll sign access permits
+    // with, so buyers can redeem them via `access_with_permit`.
+    pub fn register_access_key(&mut self, pubkey: Vec<u8>) {
+        assert_eq!(pubkey.len(), 32, "Ed25519 public keys must be 32 bytes");
+        self.owner_access_keys.insert(env::predecessor_account_id(), pubkey);
+    }
+
+    // NEW: Invalidate an outstanding permit signed by the caller so it can no
+    // longer be redeemed via `access_with_permit`.
+    pub fn revoke_permit(&mut self, signature: String) {
+        let owner = env::predecessor_account_id();
+        let mut revoked = self.revoked_permits.get(&owner).cloned().unwrap_or_default();
+        if !revoked.contains(&signature) {
+            revoked.push(signature);
+        }
+        self.revoked_permits.insert(owner, revoked);
+    }
+
+    // NEW: The epoch a permit for (p_id, buyer) must be signed under to
+    // still redeem. Owners fetch this before signing a new permit, and
+    // `revoke_buyer_access` bumps it to invalidate every permit signed
+    // under the previous epoch at once.
+    pub fn get_permit_epoch(&self, p_id: u64, buyer: AccountId) -> u64 {
+        self.permit_epochs.get(&(p_id, buyer)).copied().unwrap_or(0)
+    }
+
+    // NEW: Redeem an off-chain permit the listing owner signed with their
+    // registered access key, granting access in one buyer-initiated call
+    // instead of requiring an on-chain `grant_buyer_access` from the owner.
+    pub fn access_with_permit(
+        &mut self,
+        p_id: u64,
+        buyer: AccountId,
+        expiration: Expiration,
+        signature: String,
+    ) {
+        assert_eq!(
+            env::predecessor_account_id(), buyer,
+            "Only the buyer may redeem their own permit"
+        );
+        assert!(!expiration.is_expired(), "Permit has already expired");
+
+        let index = *self.listing_index.get(&p_id).expect("Listing not found");
+        let item = self.listings.get(index).expect("Listing not found").clone();
+
+        let revoked = self.revoked_permits
+            .get(&item.owner)
+            .map(|sigs| sigs.contains(&signature))
+            .unwrap_or(false);
+        assert!(!revoked, "This permit has been revoked");
+
+        let pubkey = self.owner_access_keys
+            .get(&item.owner)
+            .expect("Listing owner has not registered an access key")
+            .clone();
+        // Every permit is scoped to the pair's current epoch, so a single
+        // `revoke_buyer_access` invalidates ALL outstanding permits for this
+        // (product_id, buyer) — not just whichever one was last redeemed.
+        let epoch = self.get_permit_epoch(p_id, buyer.clone());
+        let message = permit_message(p_id, &buyer, expiration, epoch);
+        assert!(
+            verify_ed25519(&pubkey, &message, &signature),
+            "Invalid permit signature"
+        );
+
+        let owner = item.owner.clone();
+        let mut updated_item = item;
+        updated_item.buyers_with_access.insert(buyer.clone(), expiration);
+        self.listings.set(index, updated_item);
+
+        self.events.push(MarketEvent {
+            kind: EventKind::GrantAccess,
+            product_id: p_id,
+            actor: owner,
+            counterparty: Some(buyer),
+            timestamp: env::block_timestamp(),
+        });
+    }
+
+    // NEW: Admin-gated registration of an enclave's ed25519 public key, used
+    // to verify `tee_signature` on listings attested by that enclave.
+    pub fn register_trusted_enclave(&mut self, key_id: String, pubkey: Vec<u8>) {
+        assert_eq!(
+            env::predecessor_account_id(), self.admin,
+            "Only the contract admin can register trusted enclaves"
+        );
+        assert_eq!(pubkey.len(), 32, "Ed25519 public keys must be 32 bytes");
+
+        self.trusted_enclaves.insert(key_id, pubkey);
+    }
+
+    // NEW: Verifies `tee_signature` over the canonical Borsh-encoded
+    // `(product_id, cid, nova_group_id)` message from `tee_attestation_message`
+    // using the enclave key registered under `key_id`.
+    fn verify_tee_signature(
+        &self,
+        key_id: &str,
+        product_id: u64,
+        cid: &str,
+        nova_group_id: &str,
+        tee_signature: &str,
+    ) -> bool {
+        let Some(pubkey) = self.trusted_enclaves.get(key_id) else {
+            return false;
+        };
+        let message = tee_attestation_message(product_id, cid, nova_group_id);
+        verify_ed25519(pubkey, &message, tee_signature)
+    }
+
     pub fn create_listing(
         &mut self,
         product_id: u64,
-        price: u32,
+        price: Price,
         nova_group_id: String,
         list_type: ListingKind,
         cid: String,
         gp_owner: AccountId,
-        is_tee_verified: bool,
+        tee_key_id: Option<String>,
         tee_signature: Option<String>,
     ) {
+        assert!(price.denominator > 0, "Price denominator must be non-zero");
+        assert!(
+            !self.listing_index.contains_key(&product_id),
+            "A listing with this product_id already exists"
+        );
+
+        // UPDATED: `is_tee_verified` is no longer trusted from the caller —
+        // the contract checks the attestation signature itself.
+        let is_tee_verified = match (&tee_key_id, &tee_signature) {
+            (Some(key_id), Some(signature)) => {
+                self.verify_tee_signature(key_id, product_id, &cid, &nova_group_id, signature)
+            }
+            _ => false,
+        };
+
         let new_list = Listing {
             product_id,
             price,
             nova_group_id,
-            owner: gp_owner,
+            owner: gp_owner.clone(),
             purchase_number: 0,
             list_type,
             cid,
             is_active: true,
             buyers: Vec::new(),
-            buyers_with_access: Vec::new(),
+            buyers_with_access: HashMap::new(),
             is_tee_verified,
             tee_signature,
         };
-        
+
         self.listings.push(new_list);
+
+        let index = (self.listings.len() - 1) as u32;
+        self.listing_index.insert(product_id, index);
+
+        let mut owner_listings = self.listings_by_owner.get(&gp_owner).cloned().unwrap_or_default();
+        owner_listings.push(product_id);
+        self.listings_by_owner.insert(gp_owner, owner_listings);
+    }
+
+    // NEW: Push many listings in a single transaction. A `product_id` already
+    // in use is reported as a per-item failure rather than aborting the batch.
+    pub fn create_listings(&mut self, listings: Vec<ListingInput>) -> Vec<BatchItemResult> {
+        listings
+            .into_iter()
+            .map(|input| {
+                let product_id = input.product_id;
+                if self.listing_index.contains_key(&product_id) {
+                    return BatchItemResult {
+                        product_id,
+                        success: false,
+                        error: Some("A listing with this product_id already exists".to_string()),
+                    };
+                }
+                if input.price.denominator == 0 {
+                    return BatchItemResult {
+                        product_id,
+                        success: false,
+                        error: Some("Price denominator must be non-zero".to_string()),
+                    };
+                }
+
+                self.create_listing(
+                    input.product_id,
+                    input.price,
+                    input.nova_group_id,
+                    input.list_type,
+                    input.cid,
+                    input.gp_owner,
+                    input.tee_key_id,
+                    input.tee_signature,
+                );
+                BatchItemResult { product_id, success: true, error: None }
+            })
+            .collect()
     }
 
     pub fn get_listings(&self) -> Vec<Listing> {
         self.listings.iter().map(|l| l.clone()).collect()
     }
-    
-    // UPDATED: Now requires buyer's NOVA account ID
-    pub fn buy(&mut self, p_id: u64, nova_account_id: String) {
-        let buyer_account: AccountId = env::predecessor_account_id();
-        
-        // Store the mapping: NEAR wallet → NOVA account
-        self.nova_account_map.insert(buyer_account.clone(), nova_account_id);
-        
+
+    // NEW: Resolve a listing in O(1) via the secondary index instead of
+    // scanning `listings`.
+    pub fn get_listing(&self, p_id: u64) -> Option<Listing> {
+        let index = self.listing_index.get(&p_id)?;
+        self.listings.get(*index).cloned()
+    }
+
+    // NEW: Exact numerator/denominator so front-ends can render the price
+    // without floating-point rounding.
+    pub fn get_price_components(&self, p_id: u64) -> Option<(u128, u128)> {
+        let price = self.get_listing(p_id)?.price;
+        Some((price.numerator, price.denominator))
+    }
+
+    // NEW: All listings owned by `owner`, resolved through `listings_by_owner`.
+    pub fn get_listings_by_owner(&self, owner: AccountId) -> Vec<Listing> {
+        self.listings_by_owner
+            .get(&owner)
+            .map(|ids| ids.iter().filter_map(|id| self.get_listing(*id)).collect())
+            .unwrap_or_default()
+    }
+
+    // NEW: All listings `buyer` has purchased, resolved through `listings_by_buyer`.
+    pub fn get_purchased_listings(&self, buyer: AccountId) -> Vec<Listing> {
+        self.listings_by_buyer
+            .get(&buyer)
+            .map(|ids| ids.iter().filter_map(|id| self.get_listing(*id)).collect())
+            .unwrap_or_default()
+    }
+
+    // NEW: One-time migration that rebuilds the secondary indexes from the
+    // existing `listings` Vector. Safe to call repeatedly; entries are
+    // overwritten rather than duplicated.
+    pub fn rebuild_indexes(&mut self) {
         for i in 0..self.listings.len() {
             if let Some(item) = self.listings.get(i) {
-                if item.product_id == p_id {
-                    let mut updated_item = item.clone();
-                    
-                    updated_item.purchase_number += 1;
-                    
-                    if !updated_item.buyers.contains(&buyer_account) {
-                        updated_item.buyers.push(buyer_account.clone());
+                self.listing_index.insert(item.product_id, i as u32);
+
+                let mut owner_listings = self.listings_by_owner.get(&item.owner).cloned().unwrap_or_default();
+                if !owner_listings.contains(&item.product_id) {
+                    owner_listings.push(item.product_id);
+                }
+                self.listings_by_owner.insert(item.owner.clone(), owner_listings);
+
+                for buyer in &item.buyers {
+                    let mut buyer_listings = self.listings_by_buyer.get(buyer).cloned().unwrap_or_default();
+                    if !buyer_listings.contains(&item.product_id) {
+                        buyer_listings.push(item.product_id);
                     }
-                    
-                    self.listings.set(i, updated_item);
-                    break;
+                    self.listings_by_buyer.insert(buyer.clone(), buyer_listings);
                 }
             }
         }
     }
-    
+
+    // UPDATED: Now requires buyer's NOVA account ID, and the attached deposit
+    // must match the listing's price. The deposit is held in escrow until the
+    // owner grants access (or it's reclaimed via `refund`).
+    #[payable]
+    pub fn buy(&mut self, p_id: u64, nova_account_id: String) {
+        let buyer_account: AccountId = env::predecessor_account_id();
+
+        let index = *self.listing_index.get(&p_id).expect("Listing not found");
+        let item = self.listings.get(index).expect("Listing not found").clone();
+
+        let price_yocto = item.price.to_yocto();
+        assert_eq!(
+            env::attached_deposit().as_yoctonear(), price_yocto,
+            "Attached deposit must match the listing price"
+        );
+        assert!(
+            !self.escrow.contains_key(&(p_id, buyer_account.clone())),
+            "A deposit is already escrowed for this listing; grant or refund it first"
+        );
+
+        // Store the mapping: NEAR wallet → NOVA account
+        self.nova_account_map.insert(buyer_account.clone(), nova_account_id);
+
+        let owner = item.owner.clone();
+        let mut updated_item = item;
+
+        updated_item.purchase_number += 1;
+
+        if !updated_item.buyers.contains(&buyer_account) {
+            updated_item.buyers.push(buyer_account.clone());
+
+            let mut buyer_listings = self.listings_by_buyer.get(&buyer_account).cloned().unwrap_or_default();
+            buyer_listings.push(p_id);
+            self.listings_by_buyer.insert(buyer_account.clone(), buyer_listings);
+        }
+
+        self.listings.set(index, updated_item);
+
+        self.escrow.insert((p_id, buyer_account.clone()), EscrowEntry {
+            amount: price_yocto,
+            deposited_at: env::block_timestamp(),
+            revoked: false,
+        });
+
+        self.events.push(MarketEvent {
+            kind: EventKind::Purchase,
+            product_id: p_id,
+            actor: buyer_account,
+            counterparty: Some(owner),
+            timestamp: env::block_timestamp(),
+        });
+    }
+
+    // NEW: Reclaim an escrowed deposit after the owner revoked access before
+    // granting it, or after `escrow_timeout_ns` has elapsed.
+    pub fn refund(&mut self, p_id: u64) {
+        let buyer = env::predecessor_account_id();
+        let key = (p_id, buyer.clone());
+
+        let entry = self.escrow.get(&key).expect("No escrowed deposit for this listing").clone();
+        let timed_out = env::block_timestamp() >= entry.deposited_at + self.escrow_timeout_ns;
+        assert!(
+            entry.revoked || timed_out,
+            "Deposit can only be refunded after the owner revokes access or the escrow times out"
+        );
+
+        self.escrow.remove(&key);
+        Promise::new(buyer).transfer(NearToken::from_yoctonear(entry.amount));
+    }
+
     // NEW: Get NOVA account ID for a NEAR wallet
     pub fn get_nova_account(&self, near_wallet: AccountId) -> Option<String> {
         self.nova_account_map.get(&near_wallet).cloned()
     }
-    
+
     // NEW: Get all pending buyers with their NOVA account IDs
     pub fn get_pending_buyers_with_nova_accounts(&self, p_id: u64) -> Vec<(AccountId, String)> {
         if let Some(listing) = self.get_listing(p_id) {
             listing.buyers
                 .into_iter()
-                .filter(|buyer| !listing.buyers_with_access.contains(buyer))
+                .filter(|buyer| !has_valid_access(&listing, buyer))
                 .filter_map(|buyer| {
                     self.nova_account_map.get(&buyer).map(|nova_id| (buyer.clone(), nova_id.clone()))
                 })
@@ -124,94 +628,160 @@ impl Contract {
             Vec::new()
         }
     }
-    
-    pub fn grant_buyer_access(&mut self, p_id: u64, buyer: AccountId) {
-        let caller = env::predecessor_account_id();
-        
-        for i in 0..self.listings.len() {
-            if let Some(item) = self.listings.get(i) {
-                if item.product_id == p_id {
-                    assert_eq!(
-                        item.owner, caller,
-                        "Only the listing owner can grant access"
-                    );
-                    
-                    assert!(
-                        item.buyers.contains(&buyer),
-                        "Account has not purchased this listing"
-                    );
-                    
-                    let mut updated_item = item.clone();
-                    
-                    if !updated_item.buyers_with_access.contains(&buyer) {
-                        updated_item.buyers_with_access.push(buyer);
-                    }
-                    
-                    self.listings.set(i, updated_item);
-                    break;
+
+    pub fn grant_buyer_access(&mut self, p_id: u64, buyer: AccountId, expiration: Expiration) {
+        if let Err(reason) = self.try_grant_buyer_access(p_id, buyer, expiration) {
+            env::panic_str(&reason);
+        }
+    }
+
+    // NEW: Grant access to many (product, buyer) pairs in one transaction.
+    // Unlike `grant_buyer_access`, a rejected entry doesn't abort the whole
+    // batch — its failure is reported alongside the successes.
+    pub fn grant_buyer_access_batch(&mut self, grants: Vec<(u64, AccountId, Expiration)>) -> Vec<BatchItemResult> {
+        grants
+            .into_iter()
+            .map(|(p_id, buyer, expiration)| {
+                match self.try_grant_buyer_access(p_id, buyer, expiration) {
+                    Ok(()) => BatchItemResult { product_id: p_id, success: true, error: None },
+                    Err(reason) => BatchItemResult { product_id: p_id, success: false, error: Some(reason) },
                 }
-            }
+            })
+            .collect()
+    }
+
+    fn try_grant_buyer_access(&mut self, p_id: u64, buyer: AccountId, expiration: Expiration) -> Result<(), String> {
+        let caller = env::predecessor_account_id();
+
+        let index = *self.listing_index.get(&p_id).ok_or("Listing not found")?;
+        let item = self.listings.get(index).ok_or("Listing not found")?.clone();
+
+        if item.owner != caller {
+            return Err("Only the listing owner can grant access".to_string());
+        }
+        if !item.buyers.contains(&buyer) {
+            return Err("Account has not purchased this listing".to_string());
         }
+
+        let mut updated_item = item;
+        updated_item.buyers_with_access.insert(buyer.clone(), expiration);
+        self.listings.set(index, updated_item);
+
+        // Release the escrowed deposit to the owner now that access was granted.
+        if let Some(entry) = self.escrow.remove(&(p_id, buyer.clone())) {
+            Promise::new(caller.clone()).transfer(NearToken::from_yoctonear(entry.amount));
+        }
+
+        self.events.push(MarketEvent {
+            kind: EventKind::GrantAccess,
+            product_id: p_id,
+            actor: caller,
+            counterparty: Some(buyer),
+            timestamp: env::block_timestamp(),
+        });
+
+        Ok(())
     }
-    
+
     pub fn revoke_buyer_access(&mut self, p_id: u64, buyer: AccountId) {
-        let caller = env::predecessor_account_id();
-        
-        for i in 0..self.listings.len() {
-            if let Some(item) = self.listings.get(i) {
-                if item.product_id == p_id {
-                    assert_eq!(
-                        item.owner, caller,
-                        "Only the listing owner can revoke access"
-                    );
-                    
-                    let mut updated_item = item.clone();
-                    
-                    updated_item.buyers_with_access.retain(|b| b != &buyer);
-                    
-                    self.listings.set(i, updated_item);
-                    break;
+        if let Err(reason) = self.try_revoke_buyer_access(p_id, buyer) {
+            env::panic_str(&reason);
+        }
+    }
+
+    // NEW: Revoke access for many (product, buyer) pairs in one transaction,
+    // reporting per-item success/failure instead of aborting on the first error.
+    pub fn revoke_buyer_access_batch(&mut self, revocations: Vec<(u64, AccountId)>) -> Vec<BatchItemResult> {
+        revocations
+            .into_iter()
+            .map(|(p_id, buyer)| {
+                match self.try_revoke_buyer_access(p_id, buyer) {
+                    Ok(()) => BatchItemResult { product_id: p_id, success: true, error: None },
+                    Err(reason) => BatchItemResult { product_id: p_id, success: false, error: Some(reason) },
                 }
-            }
+            })
+            .collect()
+    }
+
+    fn try_revoke_buyer_access(&mut self, p_id: u64, buyer: AccountId) -> Result<(), String> {
+        let caller = env::predecessor_account_id();
+
+        let index = *self.listing_index.get(&p_id).ok_or("Listing not found")?;
+        let item = self.listings.get(index).ok_or("Listing not found")?.clone();
+
+        if item.owner != caller {
+            return Err("Only the listing owner can revoke access".to_string());
         }
+
+        let mut updated_item = item;
+        updated_item.buyers_with_access.remove(&buyer);
+        self.listings.set(index, updated_item);
+
+        // If a deposit is still escrowed (access was never granted), mark it
+        // refundable so the buyer can reclaim it via `refund`.
+        let key = (p_id, buyer.clone());
+        if let Some(entry) = self.escrow.get(&key) {
+            let mut updated_entry = entry.clone();
+            updated_entry.revoked = true;
+            self.escrow.insert(key, updated_entry);
+        }
+
+        // Bump the permit epoch for this (product_id, buyer) so every permit
+        // signed so far — regardless of how many the owner issued — stops
+        // verifying, not just the one redemption the contract happened to see.
+        let epoch_key = (p_id, buyer.clone());
+        let current_epoch = self.permit_epochs.get(&epoch_key).copied().unwrap_or(0);
+        self.permit_epochs.insert(epoch_key, current_epoch + 1);
+
+        self.events.push(MarketEvent {
+            kind: EventKind::RevokeAccess,
+            product_id: p_id,
+            actor: caller,
+            counterparty: Some(buyer),
+            timestamp: env::block_timestamp(),
+        });
+
+        Ok(())
     }
-    
+
     pub fn get_pending_access_buyers(&self, p_id: u64) -> Vec<AccountId> {
         if let Some(listing) = self.get_listing(p_id) {
             listing.buyers
+                .clone()
                 .into_iter()
-                .filter(|buyer| !listing.buyers_with_access.contains(buyer))
+                .filter(|buyer| !has_valid_access(&listing, buyer))
                 .collect()
         } else {
             Vec::new()
         }
     }
-    
+
+    // UPDATED: Only returns buyers whose grant hasn't expired yet.
     pub fn get_buyers_with_access(&self, p_id: u64) -> Vec<AccountId> {
         if let Some(listing) = self.get_listing(p_id) {
             listing.buyers_with_access
+                .into_iter()
+                .filter(|(_, expiration)| !expiration.is_expired())
+                .map(|(buyer, _)| buyer)
+                .collect()
         } else {
             Vec::new()
         }
     }
-    
+
     pub fn has_access(&self, p_id: u64, buyer: AccountId) -> bool {
         if let Some(listing) = self.get_listing(p_id) {
-            listing.buyers_with_access.contains(&buyer)
+            has_valid_access(&listing, &buyer)
         } else {
             false
         }
     }
-    
-    pub fn get_listing(&self, p_id: u64) -> Option<Listing> {
-        for item in self.listings.iter() {
-            if item.product_id == p_id {
-                return Some(item.clone());
-            }
-        }
-        None
+
+    // NEW: Expose the stored expiration so front-ends can show when access lapses.
+    pub fn get_access_expiration(&self, p_id: u64, buyer: AccountId) -> Option<Expiration> {
+        self.get_listing(p_id)?.buyers_with_access.get(&buyer).copied()
     }
-    
+
     pub fn has_purchased(&self, p_id: u64, account_id: AccountId) -> bool {
         if let Some(listing) = self.get_listing(p_id) {
             listing.buyers.contains(&account_id)
@@ -219,4 +789,4 @@ impl Contract {
             false
         }
     }
-}
\ No newline at end of file
+}